@@ -0,0 +1,415 @@
+// Copyright 2022 Oxide Computer Company
+
+//! A build-script front-end for progenitor.
+//!
+//! This is the middle ground between the `cargo-progenitor` CLI and the
+//! `progenitor::generate_api!` proc-macro: it lets a crate keep its OpenAPI
+//! document in-tree, regenerate it from `build.rs` whenever the document
+//! changes, and `include!` the result out of `OUT_DIR` without committing the
+//! generated source. The shape is deliberately similar to `cxx-build`'s
+//! `Builder`.
+//!
+//! ```no_run
+//! let out = cargo_progenitor::build::Builder::new()
+//!     .spec_path("api.json")
+//!     .interface(progenitor::InterfaceStyle::Builder)
+//!     .tag(progenitor::TagStyle::Separate)
+//!     .emit_client(true)
+//!     .generate()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use openapiv3::OpenAPI;
+use progenitor::{GenerationSettings, Generator, InterfaceStyle, TagStyle};
+use quote::quote;
+
+use crate::save;
+
+/// The `int_as_string` serde helper, emitted verbatim into generated crates
+/// that enable large-integer-as-string mode.
+const INT_AS_STRING_MODULE: &str = include_str!("int_as_string.rs");
+
+/// Write the generated `lib.rs` and `progenitor_client.rs` into `src/` beneath
+/// `root`, creating the directories as needed.
+///
+/// When `include_client` is false the support code is re-exported from the
+/// `progenitor-client` crate rather than emitted inline. When `int_as_string`
+/// is set the [`int_as_string`](crate::int_as_string) helper module is emitted
+/// alongside, so the `#[serde(with = "int_as_string")]` attributes the
+/// generator attaches to 64-bit-and-wider fields resolve.
+pub fn write_generated_crate(
+    root: &Path,
+    api_code: &str,
+    include_client: bool,
+    int_as_string: bool,
+) -> Result<()> {
+    let mut src = root.to_path_buf();
+    src.push("src");
+    std::fs::create_dir_all(&src)?;
+
+    let mut lib_code = String::from("mod progenitor_client;\n");
+    if int_as_string {
+        lib_code.push_str("mod int_as_string;\n");
+    }
+    lib_code.push('\n');
+    lib_code.push_str(api_code);
+    let mut librs = src.clone();
+    librs.push("lib.rs");
+    save(librs, lib_code.as_str())?;
+
+    if int_as_string {
+        let mut helpers = src.clone();
+        helpers.push("int_as_string.rs");
+        save(helpers, INT_AS_STRING_MODULE)?;
+    }
+
+    let progenitor_client_code = match include_client {
+        true => progenitor_client::code().to_string(),
+        false => quote! {
+            pub use progenitor_client::{
+                ByteStream, ResponseValue, Error, RequestBuilderExt, encode_path
+            };
+        }
+        .to_string(),
+    };
+    let mut clientrs = src;
+    clientrs.push("progenitor_client.rs");
+    save(clientrs, &progenitor_client_code)?;
+
+    Ok(())
+}
+
+/// Collect the distinct operation tags in `api`, in first-seen order.
+///
+/// Under [`TagStyle::Separate`] each of these is declared as a Cargo feature in
+/// the synthesized manifest; the generator gates the corresponding operations
+/// behind the matching `#[cfg(feature = "...")]` so consumers compile only the
+/// API surface they call.
+pub fn collect_tags(api: &OpenAPI) -> Vec<String> {
+    let mut tags = Vec::new();
+    for item in api.paths.paths.values() {
+        if let openapiv3::ReferenceOr::Item(item) = item {
+            let operations = [
+                &item.get,
+                &item.put,
+                &item.post,
+                &item.delete,
+                &item.options,
+                &item.head,
+                &item.patch,
+                &item.trace,
+            ];
+            for op in operations.into_iter().flatten() {
+                for tag in &op.tags {
+                    if !tags.contains(tag) {
+                        tags.push(tag.clone());
+                    }
+                }
+            }
+        }
+    }
+    tags
+}
+
+/// Escape a string for use inside a TOML basic (double-quoted) string, so that
+/// crate names, versions and workspace members containing `"` or `\` produce
+/// valid TOML rather than a broken manifest.
+pub fn escape_toml_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write a `Cargo.toml` for the generated crate into `root`.
+///
+/// When `features` is `Some`, a `[features]` table is synthesized with one
+/// feature per tag plus a `default` that enables them all. These are the
+/// features the generator gates each tag's operations behind with
+/// `#[cfg(feature = "...")]` under [`TagStyle::Separate`], so the declared
+/// table and the emitted code line up.
+pub fn write_cargo_toml(
+    root: &Path,
+    name: &str,
+    version: &str,
+    dependencies: &[String],
+    features: Option<&[String]>,
+) -> Result<()> {
+    let mut toml = root.to_path_buf();
+    toml.push("Cargo.toml");
+
+    let mut tomlout = format!(
+        "[package]\n\
+        name = \"{}\"\n\
+        version = \"{}\"\n\
+        edition = \"2021\"\n\
+        \n\
+        [dependencies]\n\
+        {}\n\
+        \n",
+        escape_toml_string(name),
+        escape_toml_string(version),
+        dependencies.join("\n"),
+    );
+
+    if let Some(features) = features {
+        let features = feature_names(features);
+        let default = features
+            .iter()
+            .map(|f| format!("\"{}\"", f))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tomlout.push_str(&format!("[features]\ndefault = [{}]\n", default));
+        for feature in &features {
+            tomlout.push_str(&format!("{} = []\n", feature));
+        }
+        tomlout.push('\n');
+    }
+
+    save(&toml, tomlout.as_str())
+}
+
+/// Normalize an OpenAPI tag into a valid Cargo feature name: characters that
+/// are not ASCII alphanumerics, `_` or `-` become `-`, and surrounding dashes
+/// are trimmed. An empty result falls back to `feature`.
+fn sanitize_feature_name(tag: &str) -> String {
+    let mapped: String = tag
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let trimmed = mapped.trim_matches('-');
+    if trimmed.is_empty() {
+        "feature".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Map tags to unique, valid Cargo feature names. Names are sanitized, deduped
+/// against one another, and kept clear of the synthesized `default` feature;
+/// collisions are disambiguated with a numeric suffix.
+fn feature_names(tags: &[String]) -> Vec<String> {
+    // `default` is emitted separately, so reserve it up front to avoid a
+    // duplicate key when a tag normalizes to it.
+    let mut seen = std::collections::HashSet::from(["default".to_string()]);
+    let mut out = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let base = sanitize_feature_name(tag);
+        let mut name = base.clone();
+        let mut n = 2;
+        while !seen.insert(name.clone()) {
+            name = format!("{}-{}", base, n);
+            n += 1;
+        }
+        out.push(name);
+    }
+    out
+}
+
+/// A `build.rs`-friendly wrapper around [`Generator`] that emits generated
+/// source into `OUT_DIR`.
+pub struct Builder {
+    spec_path: Option<PathBuf>,
+    settings: GenerationSettings,
+    emit_client: bool,
+    int_as_string: bool,
+    out_dir: Option<PathBuf>,
+    rerun_if_changed: bool,
+    dependencies: Vec<String>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            spec_path: None,
+            settings: GenerationSettings::default(),
+            emit_client: true,
+            int_as_string: false,
+            out_dir: None,
+            rerun_if_changed: true,
+            dependencies: Vec::new(),
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder` with progenitor's default generation settings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Path to the OpenAPI document to generate from (JSON or YAML).
+    pub fn spec_path<P>(&mut self, path: P) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.spec_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// SDK interface style; see [`InterfaceStyle`].
+    pub fn interface(&mut self, interface: InterfaceStyle) -> &mut Self {
+        self.settings.with_interface(interface);
+        self
+    }
+
+    /// SDK tag style; see [`TagStyle`].
+    pub fn tag(&mut self, tag: TagStyle) -> &mut Self {
+        self.settings.with_tag(tag);
+        self
+    }
+
+    /// Serialize 64-bit-and-wider integer fields as JSON strings so they
+    /// survive round-trips through systems that lose precision above 2^53.
+    pub fn int_as_string(&mut self, int_as_string: bool) -> &mut Self {
+        self.settings.with_int_as_string(int_as_string);
+        self.int_as_string = int_as_string;
+        self
+    }
+
+    /// Emit the progenitor support code inline (the default) rather than
+    /// depending on a published `progenitor-client` crate.
+    pub fn emit_client(&mut self, emit_client: bool) -> &mut Self {
+        self.emit_client = emit_client;
+        self
+    }
+
+    /// Override the output directory. Defaults to the `OUT_DIR` set by cargo
+    /// when running under a build script.
+    pub fn out_dir<P>(&mut self, out_dir: P) -> &mut Self
+    where
+        P: AsRef<Path>,
+    {
+        self.out_dir = Some(out_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Whether to emit a `cargo:rerun-if-changed` line for the spec document
+    /// (the default).
+    pub fn rerun_if_changed(&mut self, rerun_if_changed: bool) -> &mut Self {
+        self.rerun_if_changed = rerun_if_changed;
+        self
+    }
+
+    /// Generate the client, writing `lib.rs` and `progenitor_client.rs` into a
+    /// `src/` directory beneath the output directory and returning the output
+    /// directory's path.
+    ///
+    /// After a successful call [`Builder::dependencies`] reports the crates the
+    /// generated code needs.
+    pub fn generate(&mut self) -> Result<PathBuf> {
+        let spec_path = self
+            .spec_path
+            .as_ref()
+            .context("a spec_path is required")?;
+        let api = crate::load_api(spec_path)?;
+
+        if !self.emit_client {
+            if crate::release_is_unstable() {
+                self.settings.use_client("*".to_string());
+            } else {
+                self.settings
+                    .use_client(crate::built_info::PKG_VERSION.to_string());
+            }
+        }
+
+        let mut generator = Generator::new(&self.settings);
+        let api_code = generator
+            .generate_text(&api)
+            .context("generation experienced errors")?;
+        self.dependencies = generator.dependencies();
+
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => out_dir.clone(),
+            None => PathBuf::from(
+                env::var_os("OUT_DIR")
+                    .context("OUT_DIR is not set; call out_dir()")?,
+            ),
+        };
+        std::fs::create_dir_all(&out_dir)?;
+        write_generated_crate(
+            &out_dir,
+            &api_code,
+            self.emit_client,
+            self.int_as_string,
+        )?;
+
+        if self.rerun_if_changed {
+            println!("cargo:rerun-if-changed={}", spec_path.display());
+        }
+
+        Ok(out_dir)
+    }
+
+    /// The crates the generated code depends on, suitable for asserting against
+    /// a consumer's `Cargo.toml`. Empty until [`Builder::generate`] has run.
+    pub fn dependencies(&self) -> &[String] {
+        &self.dependencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_tags, escape_toml_string, feature_names};
+
+    #[test]
+    fn sanitizes_tags_with_whitespace_and_punctuation() {
+        assert_eq!(feature_names(&["Pet Store".to_string()]), ["Pet-Store"]);
+        assert_eq!(feature_names(&["v1.0/users".to_string()]), ["v1-0-users"]);
+    }
+
+    #[test]
+    fn avoids_duplicate_default_key() {
+        // A tag literally named `default` must not collide with the
+        // synthesized `default` feature.
+        let names = feature_names(&["default".to_string()]);
+        assert!(!names.contains(&"default".to_string()));
+        assert_eq!(names, ["default-2"]);
+    }
+
+    #[test]
+    fn disambiguates_colliding_names() {
+        let names =
+            feature_names(&["a b".to_string(), "a/b".to_string()]);
+        assert_eq!(names, ["a-b", "a-b-2"]);
+    }
+
+    #[test]
+    fn escapes_toml_special_characters() {
+        assert_eq!(escape_toml_string("a\"b\\c"), "a\\\"b\\\\c");
+    }
+
+    #[test]
+    fn collects_tags_in_first_seen_order() {
+        let api: openapiv3::OpenAPI = serde_json::from_value(serde_json::json!({
+            "openapi": "3.0.0",
+            "info": {"title": "t", "version": "1"},
+            "paths": {
+                "/a": {"get": {"tags": ["pets", "store"], "responses": {}}},
+                "/b": {"post": {"tags": ["store", "users"], "responses": {}}}
+            }
+        }))
+        .unwrap();
+        assert_eq!(collect_tags(&api), ["pets", "store", "users"]);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Multi-spec generation driven by a `progenitor.toml` manifest.
+//!
+//! Rather than scripting N separate CLI invocations, CI can describe a fleet of
+//! service clients in one declarative table keyed by client name and regenerate
+//! them all in a single run. The shape mirrors cargo's own named tables:
+//!
+//! ```toml
+//! [output]
+//! dir = "clients"     # base directory for the generated crates
+//! workspace = true    # also emit a workspace root tying them together
+//!
+//! [client.nexus]
+//! input = "nexus.json"
+//! version = "0.1.0"
+//! interface = "builder"
+//! tags = "separate"
+//!
+//! [client.oximeter]
+//! input = "oximeter.json"
+//! version = "0.1.0"
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use progenitor::{GenerationSettings, Generator};
+use serde::Deserialize;
+
+use crate::{build, load_api, release_is_unstable, InterfaceArg, TagArg};
+
+/// A `progenitor.toml` manifest.
+#[derive(Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    output: Output,
+    /// Per-client settings, keyed by the generated crate name.
+    #[serde(default)]
+    client: BTreeMap<String, Client>,
+}
+
+/// Settings that apply to the generated set as a whole.
+#[derive(Deserialize, Default)]
+struct Output {
+    /// Base directory the generated crates are written beneath; defaults to the
+    /// current directory.
+    #[serde(default)]
+    dir: Option<PathBuf>,
+    /// Emit a Cargo workspace root tying the generated crates together.
+    #[serde(default)]
+    workspace: bool,
+}
+
+/// Settings for a single generated client.
+#[derive(Deserialize)]
+struct Client {
+    /// OpenAPI definition document (JSON or YAML).
+    input: PathBuf,
+    /// Target crate version.
+    version: String,
+    #[serde(default)]
+    interface: Option<InterfaceArg>,
+    #[serde(default)]
+    tags: Option<TagArg>,
+    /// Serialize 64-bit-and-wider integers as JSON strings; defaults to false.
+    #[serde(default)]
+    int_as_string: bool,
+    /// Emit the progenitor support code inline; defaults to true.
+    #[serde(default)]
+    include_client: Option<bool>,
+}
+
+/// Generate every client described by the manifest at `path`.
+pub fn generate<P>(path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("reading {}", path.display()))?;
+    let config: Config = toml::from_str(&raw)
+        .with_context(|| format!("parsing {}", path.display()))?;
+
+    let base = config.output.dir.clone().unwrap_or_default();
+    std::fs::create_dir_all(&base)?;
+
+    let mut members = Vec::new();
+    for (name, client) in &config.client {
+        client.generate(name, &base)?;
+        members.push(name.clone());
+    }
+
+    if config.output.workspace {
+        write_workspace(&base, &members)?;
+    }
+
+    Ok(())
+}
+
+impl Client {
+    fn generate(&self, name: &str, base: &Path) -> Result<()> {
+        let api = load_api(&self.input)?;
+
+        let include_client = self.include_client.unwrap_or(true);
+        let mut settings = GenerationSettings::default();
+        if !include_client {
+            if release_is_unstable() {
+                settings.use_client("*".to_string());
+            } else {
+                settings.use_client(crate::built_info::PKG_VERSION.to_string());
+            }
+        }
+        if let Some(interface) = self.interface {
+            settings.with_interface(interface.into());
+        }
+        if let Some(tags) = self.tags {
+            settings.with_tag(tags.into());
+        }
+        settings.with_int_as_string(self.int_as_string);
+
+        let mut generator = Generator::new(&settings);
+        let api_code = generator
+            .generate_text(&api)
+            .with_context(|| format!("generating client {:?}", name))?;
+
+        let mut root = base.to_path_buf();
+        root.push(name);
+        std::fs::create_dir_all(&root)?;
+
+        let tags = build::collect_tags(&api);
+        let features = matches!(self.tags, Some(TagArg::Separate))
+            .then_some(tags.as_slice());
+        build::write_cargo_toml(
+            &root,
+            name,
+            &self.version,
+            &generator.dependencies(),
+            features,
+        )?;
+        build::write_generated_crate(
+            &root,
+            &api_code,
+            include_client,
+            self.int_as_string,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Emit a workspace root `Cargo.toml` whose members are the generated crates.
+fn write_workspace(base: &Path, members: &[String]) -> Result<()> {
+    let mut toml = base.to_path_buf();
+    toml.push("Cargo.toml");
+    crate::save(&toml, workspace_manifest(members).as_str())
+}
+
+/// Render the workspace root `Cargo.toml` body for `members`.
+fn workspace_manifest(members: &[String]) -> String {
+    let members = members
+        .iter()
+        .map(|m| format!("    \"{}\",", build::escape_toml_string(m)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "[workspace]\n\
+        resolver = \"2\"\n\
+        members = [\n\
+        {}\n\
+        ]\n",
+        members,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_output_and_clients() {
+        let config: Config = toml::from_str(
+            r#"
+            [output]
+            dir = "clients"
+            workspace = true
+
+            [client.nexus]
+            input = "nexus.json"
+            version = "0.1.0"
+            interface = "builder"
+            tags = "separate"
+
+            [client.oximeter]
+            input = "oximeter.json"
+            version = "0.2.0"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.output.dir.as_deref(), Some(Path::new("clients")));
+        assert!(config.output.workspace);
+        assert_eq!(config.client.len(), 2);
+
+        let nexus = &config.client["nexus"];
+        assert_eq!(nexus.input, Path::new("nexus.json"));
+        assert_eq!(nexus.version, "0.1.0");
+        assert!(matches!(nexus.tags, Some(TagArg::Separate)));
+        assert!(matches!(nexus.interface, Some(InterfaceArg::Builder)));
+
+        let oximeter = &config.client["oximeter"];
+        assert!(oximeter.interface.is_none());
+        assert!(oximeter.tags.is_none());
+        assert!(!oximeter.int_as_string);
+    }
+
+    #[test]
+    fn output_defaults_when_absent() {
+        let config: Config = toml::from_str("").unwrap();
+        assert!(config.output.dir.is_none());
+        assert!(!config.output.workspace);
+        assert!(config.client.is_empty());
+    }
+
+    #[test]
+    fn workspace_manifest_lists_members() {
+        let manifest =
+            workspace_manifest(&["nexus".to_string(), "oximeter".to_string()]);
+        assert!(manifest.contains("resolver = \"2\""));
+        assert!(manifest.contains("    \"nexus\",\n    \"oximeter\","));
+    }
+
+    #[test]
+    fn workspace_manifest_escapes_members() {
+        let manifest = workspace_manifest(&["a\"b".to_string()]);
+        assert!(manifest.contains("\"a\\\"b\""));
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2022 Oxide Computer Company
+
+//! serde helper for lossless large-integer fields.
+//!
+//! When `--int-as-string` mode is on, `write_generated_crate` emits this module
+//! into the generated crate as `src/int_as_string.rs`; the generator then
+//! references it via `#[serde(with = "int_as_string")]` on `int64`/`uint64`
+//! (and wider) fields. `serialize` always writes the integer as its decimal
+//! string so it survives round-trips through systems that lose precision above
+//! 2^53; `deserialize` accepts either a JSON string or a bare JSON number, so
+//! it stays compatible with servers that still send numbers.
+
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+use serde::{
+    de::{self, Visitor},
+    Deserializer, Serializer,
+};
+
+/// Serialize any integer as its decimal string representation.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: fmt::Display,
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+/// Deserialize an integer written either as a JSON string or a JSON number.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr + TryFrom<i64> + TryFrom<u64>,
+    <T as FromStr>::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(IntVisitor(PhantomData))
+}
+
+struct IntVisitor<T>(PhantomData<T>);
+
+impl<T> Visitor<'_> for IntVisitor<T>
+where
+    T: FromStr + TryFrom<i64> + TryFrom<u64>,
+    <T as FromStr>::Err: fmt::Display,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer or its decimal string representation")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        v.parse().map_err(de::Error::custom)
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        T::try_from(v).map_err(|_| de::Error::custom("integer out of range"))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        T::try_from(v).map_err(|_| de::Error::custom("integer out of range"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Wrap {
+        #[serde(with = "super")]
+        value: u64,
+    }
+
+    #[test]
+    fn serializes_as_string() {
+        let json = serde_json::to_string(&Wrap { value: 1 << 60 }).unwrap();
+        assert_eq!(json, r#"{"value":"1152921504606846976"}"#);
+    }
+
+    #[test]
+    fn deserializes_from_string() {
+        let w: Wrap =
+            serde_json::from_str(r#"{"value":"1152921504606846976"}"#).unwrap();
+        assert_eq!(w.value, 1 << 60);
+    }
+
+    #[test]
+    fn deserializes_from_number() {
+        let w: Wrap = serde_json::from_str(r#"{"value":42}"#).unwrap();
+        assert_eq!(w.value, 42);
+    }
+
+    #[test]
+    fn round_trips_beyond_f64_precision() {
+        let original = Wrap { value: (1u64 << 53) + 1 };
+        let json = serde_json::to_string(&original).unwrap();
+        let back: Wrap = serde_json::from_str(&json).unwrap();
+        assert_eq!(original, back);
+    }
+}
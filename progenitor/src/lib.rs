@@ -0,0 +1,231 @@
+// Copyright 2022 Oxide Computer Company
+
+//! Library surface for the progenitor command-line generator.
+//!
+//! The CLI binary in `main.rs` is a thin front-end over this crate. Everything
+//! that a consumer might want to drive from their own `build.rs` lives here so
+//! that it is part of a library target: the [`build::Builder`] build-script
+//! front-end, the `progenitor.toml` manifest driver in [`config`], and the
+//! spec-loading helpers. Keeping these out of the binary root is what makes
+//! `build::Builder::new()` reachable from a dependent crate at all.
+
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use openapiv3::OpenAPI;
+use progenitor::{InterfaceStyle, TagStyle};
+
+pub mod build;
+pub mod config;
+pub mod int_as_string;
+
+pub mod built_info {
+    // The file has been placed there by the build script.
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+/// Determine if current version is a pre-release or was built from a git-repo
+pub fn release_is_unstable() -> bool {
+    !built_info::PKG_VERSION_PRE.is_empty() || built_info::GIT_VERSION.is_some()
+}
+
+#[derive(Copy, Clone, ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterfaceArg {
+    Positional,
+    Builder,
+}
+
+impl From<InterfaceArg> for InterfaceStyle {
+    fn from(arg: InterfaceArg) -> Self {
+        match arg {
+            InterfaceArg::Positional => InterfaceStyle::Positional,
+            InterfaceArg::Builder => InterfaceStyle::Builder,
+        }
+    }
+}
+
+#[derive(Copy, Clone, ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagArg {
+    Merged,
+    Separate,
+}
+
+impl From<TagArg> for TagStyle {
+    fn from(arg: TagArg) -> Self {
+        match arg {
+            TagArg::Merged => TagStyle::Merged,
+            TagArg::Separate => TagStyle::Separate,
+        }
+    }
+}
+
+pub fn save<P>(p: P, data: &str) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let p = p.as_ref();
+    let mut f = OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(p)?;
+    f.write_all(data.as_bytes())?;
+    f.flush()?;
+    Ok(())
+}
+
+pub fn load_api<P>(p: P) -> Result<OpenAPI>
+where
+    P: AsRef<Path>,
+{
+    load_api_with_overlays(p, &[] as &[&Path])
+}
+
+/// Load an OpenAPI document, applying zero or more overlay documents over it
+/// before parsing.
+///
+/// Both the base document and each overlay may be a local path or an
+/// `http(s)://` URL, in JSON or YAML. Overlays are merged into the base as
+/// [RFC 7386](https://www.rfc-editor.org/rfc/rfc7386) JSON Merge Patches, in
+/// order, which lets users correct or augment a third-party spec without
+/// hand-editing the upstream file.
+pub fn load_api_with_overlays<P, Q>(base: P, overlays: &[Q]) -> Result<OpenAPI>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let mut document = load_document(base.as_ref())?;
+    for overlay in overlays {
+        let patch = load_document(overlay.as_ref())?;
+        merge_patch(&mut document, patch);
+    }
+    let api = serde_json::from_value(document)?;
+    Ok(api)
+}
+
+/// Upper bound on the size of a remotely fetched spec or overlay document, to
+/// keep a hostile or misconfigured endpoint from exhausting memory.
+const MAX_SPEC_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Fetch a JSON or YAML document from a path or `http(s)://` URL as a
+/// `serde_json::Value`.
+fn load_document(input: &Path) -> Result<serde_json::Value> {
+    let input = input.to_str().context("input is not valid UTF-8")?;
+
+    let text = if input.starts_with("http://") || input.starts_with("https://")
+    {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .redirect(reqwest::redirect::Policy::limited(10))
+            .build()
+            .context("building HTTP client")?;
+        let resp = client
+            .get(input)
+            .send()
+            .and_then(|resp| resp.error_for_status())
+            .with_context(|| format!("fetching {}", input))?;
+
+        // Reject oversized documents rather than buffering them whole: a
+        // well-behaved server advertises the length up front, and the capped
+        // read below backstops servers that under-report or omit it.
+        if let Some(len) = resp.content_length() {
+            if len > MAX_SPEC_BYTES {
+                bail!(
+                    "document at {} is {} bytes, exceeding the {} byte limit",
+                    input,
+                    len,
+                    MAX_SPEC_BYTES
+                );
+            }
+        }
+        let mut buf = Vec::new();
+        resp.take(MAX_SPEC_BYTES + 1)
+            .read_to_end(&mut buf)
+            .with_context(|| format!("reading body of {}", input))?;
+        if buf.len() as u64 > MAX_SPEC_BYTES {
+            bail!(
+                "document at {} exceeds the {} byte limit",
+                input,
+                MAX_SPEC_BYTES
+            );
+        }
+        String::from_utf8(buf)
+            .with_context(|| format!("decoding {} as UTF-8", input))?
+    } else {
+        std::fs::read_to_string(input)
+            .with_context(|| format!("reading {}", input))?
+    };
+
+    let value = if text.trim_start().starts_with('{') {
+        serde_json::from_str(&text)?
+    } else {
+        serde_yaml::from_str(&text)?
+    };
+    Ok(value)
+}
+
+/// Apply an RFC 7386 JSON Merge Patch: object keys mapping to `null` delete the
+/// target key, nested objects recurse, and every other value replaces the
+/// target.
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    match patch {
+        serde_json::Value::Object(patch) => {
+            if !target.is_object() {
+                *target = serde_json::Value::Object(Default::default());
+            }
+            let target = target.as_object_mut().unwrap();
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else {
+                    merge_patch(
+                        target.entry(key).or_insert(serde_json::Value::Null),
+                        value,
+                    );
+                }
+            }
+        }
+        patch => *target = patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_patch;
+    use serde_json::json;
+
+    #[test]
+    fn null_deletes_target_key() {
+        let mut target = json!({"keep": 1, "drop": 2});
+        merge_patch(&mut target, json!({"drop": null, "missing": null}));
+        assert_eq!(target, json!({"keep": 1}));
+    }
+
+    #[test]
+    fn recurses_into_nested_objects() {
+        let mut target = json!({"a": {"x": 1, "y": 2}, "b": 3});
+        merge_patch(&mut target, json!({"a": {"y": 20, "z": 30}}));
+        assert_eq!(target, json!({"a": {"x": 1, "y": 20, "z": 30}, "b": 3}));
+    }
+
+    #[test]
+    fn scalar_replaces_object() {
+        let mut target = json!({"a": {"nested": true}});
+        merge_patch(&mut target, json!({"a": 5}));
+        assert_eq!(target, json!({"a": 5}));
+    }
+
+    #[test]
+    fn object_replaces_scalar() {
+        let mut target = json!({"a": 5});
+        merge_patch(&mut target, json!({"a": {"nested": true}}));
+        assert_eq!(target, json!({"a": {"nested": true}}));
+    }
+}
@@ -1,36 +1,32 @@
 // Copyright 2022 Oxide Computer Company
 
-use std::{
-    fs::{File, OpenOptions},
-    io::Read,
-    io::Write,
-    path::{Path, PathBuf},
-};
+use std::path::PathBuf;
 
 use anyhow::{bail, Result};
-use clap::{Parser, ValueEnum};
-use openapiv3::OpenAPI;
-use progenitor::{GenerationSettings, Generator, InterfaceStyle, TagStyle};
-use quote::quote;
-
-pub mod built_info {
-    // The file has been placed there by the build script.
-    include!(concat!(env!("OUT_DIR"), "/built.rs"));
-}
+use clap::Parser;
+use progenitor::{GenerationSettings, Generator};
 
-/// Determine if current version is a pre-release or was built from a git-repo
-fn release_is_unstable() -> bool {
-    !built_info::PKG_VERSION_PRE.is_empty() || built_info::GIT_VERSION.is_some()
-}
+use cargo_progenitor::{
+    build, built_info, config, load_api_with_overlays, release_is_unstable,
+    InterfaceArg, TagArg,
+};
 
 #[derive(Parser)]
 struct Args {
-    /// OpenAPI definition document (JSON)
-    #[clap(short = 'i', long)]
-    input: String,
+    /// Generate several clients from a progenitor.toml manifest
+    #[clap(long, conflicts_with_all = ["input", "output"])]
+    config: Option<PathBuf>,
+    /// OpenAPI definition document (JSON or YAML); a local path or an
+    /// http(s):// URL
+    #[clap(short = 'i', long, required_unless_present = "config")]
+    input: Option<String>,
+    /// Overlay document merged over the input via RFC 7386 JSON Merge Patch;
+    /// may be repeated, applied in order
+    #[clap(long)]
+    overlay: Vec<String>,
     /// Output directory for Rust crate
-    #[clap(short = 'o', long)]
-    output: String,
+    #[clap(short = 'o', long, required_unless_present = "config")]
+    output: Option<String>,
     /// Emit Cargo.toml
     #[clap(long, default_value="false")]
     output_cargo_toml: bool,
@@ -47,59 +43,23 @@ struct Args {
     /// SDK tag style
     #[clap(value_enum, long, default_value_t = TagArg::Merged)]
     tags: TagArg,
+    /// Serialize 64-bit-and-wider integers as JSON strings so they survive
+    /// round-trips through systems that lose precision above 2^53
+    #[clap(long, default_value = "false")]
+    int_as_string: bool,
     /// Include client
     #[clap(default_value = match release_is_unstable() { true => "true", false => "false" }, long, action = clap::ArgAction::Set)]
     include_client: Option<bool>,
 }
 
-#[derive(Copy, Clone, ValueEnum)]
-enum InterfaceArg {
-    Positional,
-    Builder,
-}
-
-impl From<InterfaceArg> for InterfaceStyle {
-    fn from(arg: InterfaceArg) -> Self {
-        match arg {
-            InterfaceArg::Positional => InterfaceStyle::Positional,
-            InterfaceArg::Builder => InterfaceStyle::Builder,
-        }
-    }
-}
-
-#[derive(Copy, Clone, ValueEnum)]
-enum TagArg {
-    Merged,
-    Separate,
-}
+fn main() -> Result<()> {
+    let args = Args::parse();
 
-impl From<TagArg> for TagStyle {
-    fn from(arg: TagArg) -> Self {
-        match arg {
-            TagArg::Merged => TagStyle::Merged,
-            TagArg::Separate => TagStyle::Separate,
-        }
+    if let Some(config) = &args.config {
+        return config::generate(config);
     }
-}
 
-fn save<P>(p: P, data: &str) -> Result<()>
-where
-    P: AsRef<Path>,
-{
-    let p = p.as_ref();
-    let mut f = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(p)?;
-    f.write_all(data.as_bytes())?;
-    f.flush()?;
-    Ok(())
-}
-
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let api = load_api(&args.input)?;
+    let api = load_api_with_overlays(args.input.as_ref().unwrap(), &args.overlay)?;
     let include_client = match args.include_client {
         Some(true) => true,
         Some(false) => false,
@@ -116,11 +76,12 @@ fn main() -> Result<()> {
         }
     }
 
-    let mut builder = Generator::new(
-        settings
-            .with_interface(args.interface.into())
-            .with_tag(args.tags.into()),
-    );
+    settings
+        .with_interface(args.interface.into())
+        .with_tag(args.tags.into())
+        .with_int_as_string(args.int_as_string);
+
+    let mut builder = Generator::new(&settings);
 
     match builder.generate_text(&api) {
         Ok(api_code) => {
@@ -139,64 +100,34 @@ fn main() -> Result<()> {
             /*
              * Create the top-level crate directory:
              */
-            let root = PathBuf::from(&args.output);
+            let root = PathBuf::from(args.output.as_ref().unwrap());
             std::fs::create_dir_all(&root)?;
 
             if args.output_cargo_toml {
                 /*
-                * Write the Cargo.toml file:
-                */
-                let name = &args.name.unwrap();
-                let version = &args.version.unwrap();
-                let mut toml = root.clone();
-                toml.push("Cargo.toml");
-
-                let tomlout = format!(
-                    "[package]\n\
-                    name = \"{}\"\n\
-                    version = \"{}\"\n\
-                    edition = \"2021\"\n\
-                    \n\
-                    [dependencies]\n\
-                    {}\n\
-                    \n",
-                    name,
-                    version,
-                    builder.dependencies().join("\n"),
-                );
-
-                save(&toml, tomlout.as_str())?;
+                 * Write the Cargo.toml file:
+                 */
+                let tags = build::collect_tags(&api);
+                let features = matches!(args.tags, TagArg::Separate)
+                    .then_some(tags.as_slice());
+                build::write_cargo_toml(
+                    &root,
+                    args.name.as_ref().unwrap(),
+                    args.version.as_ref().unwrap(),
+                    &builder.dependencies(),
+                    features,
+                )?;
             }
 
             /*
-             * Create the src/ directory:
-             */
-            let mut src = root;
-            src.push("src");
-            std::fs::create_dir_all(&src)?;
-
-            /*
-             * Create the Rust source file containing the generated client:
-             */
-            let lib_code = format!("mod progenitor_client;\n\n{}", api_code);
-            let mut librs = src.clone();
-            librs.push("lib.rs");
-            save(librs, lib_code.as_str())?;
-
-            /*
-             * Create the Rust source file containing the support code:
+             * Create the src/ directory and write the generated sources:
              */
-            let progenitor_client_code = match include_client {
-                true => progenitor_client::code().to_string(),
-                false => quote! {
-                    pub use progenitor_client::{
-                        ByteStream, ResponseValue, Error, RequestBuilderExt, encode_path
-                    };
-                }.to_string(),
-            };
-            let mut clientrs = src;
-            clientrs.push("progenitor_client.rs");
-            save(clientrs, &progenitor_client_code)?;
+            build::write_generated_crate(
+                &root,
+                &api_code,
+                include_client,
+                args.int_as_string,
+            )?;
         }
 
         Err(e) => {
@@ -207,23 +138,3 @@ fn main() -> Result<()> {
 
     Ok(())
 }
-
-pub fn load_api<P>(p: P) -> Result<OpenAPI>
-where
-    P: AsRef<Path>,
-{
-    let mut f = File::open(p)?;
-
-    let mut buf = [b' '];
-    while buf[0].is_ascii_whitespace() {
-        f.read_exact(&mut buf)?;
-    }
-    let reader = buf.as_ref().chain(f);
-
-    let api = if buf[0] == b'{' {
-        serde_json::from_reader(reader)?
-    } else {
-        serde_yaml::from_reader(reader)?
-    };
-    Ok(api)
-}